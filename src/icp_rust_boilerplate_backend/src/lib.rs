@@ -5,12 +5,19 @@ use candid::{Decode, Encode}; // Import Decode and Encode from the candid librar
 use ic_cdk::api::time; // Import the time API from ic_cdk
 use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory}; // Import memory management structures from ic_stable_structures
 use ic_stable_structures::{BoundedStorable, Cell, DefaultMemoryImpl, StableBTreeMap, Storable}; // Import stable structures
-use std::{borrow::Cow, cell::RefCell}; // Import Cow and RefCell from the standard library
+use std::{borrow::Cow, cell::RefCell, time::Duration}; // Import Cow, RefCell and Duration from the standard library
 
 type Memory = VirtualMemory<DefaultMemoryImpl>; // Type alias for VirtualMemory using DefaultMemoryImpl
 type IdCell = Cell<u64, Memory>; // Type alias for Cell storing u64 with Memory
+type ConfigCell = Cell<Config, Memory>; // Type alias for Cell storing Config with Memory
 
-#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)] // Derive macros for InventoryItem struct
+const MIN_ITEM_NAME_LEN: u32 = 1; // Smallest value config.max_item_name_len may be set to
+const MAX_ITEM_NAME_LEN: u32 = 200; // Largest value config.max_item_name_len may be set to; keeps InventoryItem well within BoundedStorable::MAX_SIZE under every codec
+const MIN_SYNC_PAGE_SIZE: u32 = 1; // Smallest value config.sync_page_size may be set to
+const MAX_SYNC_PAGE_SIZE: u32 = 1000; // Largest value config.sync_page_size may be set to, bounding a single sync_since payload
+const DEFAULT_SYNC_PAGE_SIZE: u32 = 100; // sync_page_size used before Config has been initialized via init
+
+#[derive(candid::CandidType, Clone, Debug, PartialEq, Serialize, Deserialize, Default)] // Derive macros for InventoryItem struct
 struct InventoryItem {
     id: u64, // Unique identifier for the item
     name: String, // Name of the item
@@ -20,23 +27,174 @@ struct InventoryItem {
     updated_at: Option<u64>, // Optional timestamp of when the item was last updated
 }
 
+// A pluggable encoding for InventoryItem, so the on-disk format can be swapped at compile time
+trait ItemCodec {
+    fn encode(item: &InventoryItem) -> Vec<u8>;
+    fn decode(bytes: &[u8]) -> InventoryItem;
+}
+
+// The original Candid-based encoding; verbose on disk but needs no extra dependency
+struct CandidCodec;
+
+impl ItemCodec for CandidCodec {
+    fn encode(item: &InventoryItem) -> Vec<u8> {
+        Encode!(item).unwrap() // Serialize the InventoryItem struct to Candid bytes
+    }
+
+    fn decode(bytes: &[u8]) -> InventoryItem {
+        Decode!(bytes, InventoryItem).unwrap() // Deserialize Candid bytes to an InventoryItem struct
+    }
+}
+
+// A compact, self-describing encoding; typically shrinks string-heavy records versus Candid
+struct CborCodec;
+
+impl ItemCodec for CborCodec {
+    fn encode(item: &InventoryItem) -> Vec<u8> {
+        serde_cbor::to_vec(item).unwrap() // Serialize the InventoryItem struct to CBOR bytes
+    }
+
+    fn decode(bytes: &[u8]) -> InventoryItem {
+        serde_cbor::from_slice(bytes).unwrap() // Deserialize CBOR bytes to an InventoryItem struct
+    }
+}
+
+#[cfg(feature = "cbor")]
+type ActiveItemCodec = CborCodec; // Select the CBOR codec when the "cbor" feature is enabled
+
+#[cfg(not(feature = "cbor"))]
+type ActiveItemCodec = CandidCodec; // Default to the Candid codec
+
 // Implement the Storable trait for InventoryItem struct
 impl Storable for InventoryItem {
     fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
-        Cow::Owned(Encode!(self).unwrap()) // Serialize the InventoryItem struct to bytes
+        Cow::Owned(ActiveItemCodec::encode(self)) // Serialize the InventoryItem struct through the active codec
     }
 
     fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
-        Decode!(bytes.as_ref(), Self).unwrap() // Deserialize bytes to an InventoryItem struct
+        ActiveItemCodec::decode(bytes.as_ref()) // Deserialize bytes to an InventoryItem struct through the active codec
     }
 }
 
+#[cfg(feature = "cbor")]
+const ITEM_NAME_MAX_BYTES: u32 = MAX_ITEM_NAME_LEN * 4; // Worst-case UTF-8 bytes for a name at the configured cap
+#[cfg(feature = "cbor")]
+const ITEM_FIXED_FIELDS_OVERHEAD: u32 = 128; // Headroom for id/quantity/price/timestamps plus codec framing
+
 // Implement the BoundedStorable trait for InventoryItem struct
 impl BoundedStorable for InventoryItem {
+    // Tied to MAX_ITEM_NAME_LEN rather than asserted, so a near-cap name can never push the CBOR
+    // encoding past MAX_SIZE and panic on insert
+    #[cfg(feature = "cbor")]
+    const MAX_SIZE: u32 = ITEM_NAME_MAX_BYTES + ITEM_FIXED_FIELDS_OVERHEAD; // Maximum size of the serialized InventoryItem in bytes
+    #[cfg(not(feature = "cbor"))]
     const MAX_SIZE: u32 = 1024; // Maximum size of the serialized InventoryItem in bytes
     const IS_FIXED_SIZE: bool = false; // Indicates that the size is not fixed
 }
 
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)] // Derive macros for MovementReason enum
+enum MovementReason {
+    Add, // Stock created alongside a brand new item
+    Restock, // Stock replenished by a supplier delivery
+    Sale, // Stock consumed by a completed sale
+    Adjustment, // Stock corrected via a direct quantity edit
+    Delete, // Stock removed because the item was deleted
+    Reserve, // Stock held against a pending checkout
+    ReservationCommit, // A reservation was finalized into a sale
+    ReservationRelease, // A reservation was rolled back, returning stock
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)] // Derive macros for StockMovement struct
+struct StockMovement {
+    id: u64, // Unique, monotonically increasing movement id
+    item_id: u64, // Id of the item this movement applies to
+    delta: i64, // Signed change in quantity applied by this movement
+    reason: MovementReason, // Why the quantity changed
+    balance_after: u32, // Resulting quantity immediately after this movement
+    at: u64, // Timestamp the movement was recorded
+}
+
+// Implement the Storable trait for StockMovement struct
+impl Storable for StockMovement {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap()) // Serialize the StockMovement struct to bytes
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap() // Deserialize bytes to a StockMovement struct
+    }
+}
+
+// Implement the BoundedStorable trait for StockMovement struct
+impl BoundedStorable for StockMovement {
+    const MAX_SIZE: u32 = 256; // Maximum size of the serialized StockMovement in bytes
+    const IS_FIXED_SIZE: bool = false; // Indicates that the size is not fixed
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)] // Derive macros for Reservation struct
+struct Reservation {
+    id: u64, // Unique reservation id, shared with its originating movement id
+    item_id: u64, // Id of the item being held
+    quantity: u32, // Quantity held by this reservation
+    movement_id: u64, // Id of the StockMovement that decremented stock for this reservation
+}
+
+// Implement the Storable trait for Reservation struct
+impl Storable for Reservation {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap()) // Serialize the Reservation struct to bytes
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap() // Deserialize bytes to a Reservation struct
+    }
+}
+
+// Implement the BoundedStorable trait for Reservation struct
+impl BoundedStorable for Reservation {
+    const MAX_SIZE: u32 = 128; // Maximum size of the serialized Reservation in bytes
+    const IS_FIXED_SIZE: bool = false; // Indicates that the size is not fixed
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)] // Derive macros for Config struct
+struct Config {
+    low_stock_threshold: u32, // Quantity at or below which an item is considered low stock
+    max_item_name_len: u32, // Maximum allowed length, in characters, of an item name
+    config_poll_secs: u64, // Interval, in seconds, between low-stock sweeps; the sweep timer is registered with this value only in init and post_upgrade, so a value changed by set_config takes effect at the next upgrade
+    sync_page_size: u32, // Maximum number of changed items returned by a single sync_since call
+    version: u16, // Bumped on every set_config call so callers can detect staleness
+}
+
+// A non-derived Default so the pre-init placeholder still yields a usable sync_page_size
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            low_stock_threshold: 0,
+            max_item_name_len: 0,
+            config_poll_secs: 0,
+            sync_page_size: DEFAULT_SYNC_PAGE_SIZE,
+            version: 0,
+        }
+    }
+}
+
+// Implement the Storable trait for Config struct
+impl Storable for Config {
+    fn to_bytes(&self) -> std::borrow::Cow<[u8]> {
+        Cow::Owned(Encode!(self).unwrap()) // Serialize the Config struct to bytes
+    }
+
+    fn from_bytes(bytes: std::borrow::Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).unwrap() // Deserialize bytes to a Config struct
+    }
+}
+
+// Implement the BoundedStorable trait for Config struct
+impl BoundedStorable for Config {
+    const MAX_SIZE: u32 = 64; // Maximum size of the serialized Config in bytes
+    const IS_FIXED_SIZE: bool = false; // Indicates that the size is not fixed
+}
+
 thread_local! {
     static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> = RefCell::new(
         MemoryManager::init(DefaultMemoryImpl::default())
@@ -51,6 +209,134 @@ thread_local! {
         RefCell::new(StableBTreeMap::init(
             MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(1)))
     ));
+
+    static MOVEMENTS: RefCell<StableBTreeMap<u64, StockMovement, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(2)))
+    ));
+
+    static MOVEMENT_ID_COUNTER: RefCell<IdCell> = RefCell::new(
+        IdCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(3))), 0)
+            .expect("Cannot create a movement counter")
+    );
+
+    static RESERVATIONS: RefCell<StableBTreeMap<u64, Reservation, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(4)))
+    ));
+
+    static DELETED_ITEMS: RefCell<StableBTreeMap<u64, u64, Memory>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(5)))
+    ));
+
+    static CONFIG: RefCell<ConfigCell> = RefCell::new(
+        ConfigCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(6))), Config::default())
+            .expect("Cannot create a config cell")
+    );
+
+    static LOW_STOCK: RefCell<Vec<u64>> = RefCell::new(Vec::new());
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)] // Derive macros for InitArg struct
+struct InitArg {
+    low_stock_threshold: u32, // Initial quantity at or below which an item is considered low stock
+    max_item_name_len: u32, // Initial maximum allowed length, in characters, of an item name
+    config_poll_secs: u64, // Initial interval, in seconds, between low-stock sweeps
+    sync_page_size: u32, // Initial maximum number of changed items returned by a single sync_since call
+}
+
+// Keep config.max_item_name_len within a range that's always sensible and always safe for the
+// InventoryItem codecs, rather than letting a stray 0 (or huge value) through from the caller
+fn clamp_max_item_name_len(value: u32) -> u32 {
+    value.clamp(MIN_ITEM_NAME_LEN, MAX_ITEM_NAME_LEN)
+}
+
+// Keep config.sync_page_size within a range that always makes progress and always bounds a
+// sync_since payload to something reasonable
+fn clamp_sync_page_size(value: u32) -> u32 {
+    value.clamp(MIN_SYNC_PAGE_SIZE, MAX_SYNC_PAGE_SIZE)
+}
+
+#[ic_cdk::init] // Mark the function as the canister init entry point
+fn init(arg: InitArg) {
+    let config = Config {
+        low_stock_threshold: arg.low_stock_threshold,
+        max_item_name_len: clamp_max_item_name_len(arg.max_item_name_len),
+        config_poll_secs: arg.config_poll_secs,
+        sync_page_size: clamp_sync_page_size(arg.sync_page_size),
+        version: 0,
+    };
+    CONFIG.with(|c| c.borrow_mut().set(config)).expect("Cannot set initial config");
+
+    ic_cdk_timers::set_timer_interval(Duration::from_secs(arg.config_poll_secs), sweep_low_stock);
+}
+
+// Re-register the periodic low-stock sweep after an upgrade; init only runs on first install, and
+// ic_cdk_timers does not persist timers across upgrades even though Config survives in stable memory
+#[ic_cdk::post_upgrade] // Mark the function as the canister post-upgrade entry point
+fn post_upgrade() {
+    let config_poll_secs = CONFIG.with(|c| c.borrow().get().config_poll_secs);
+    ic_cdk_timers::set_timer_interval(Duration::from_secs(config_poll_secs), sweep_low_stock);
+}
+
+#[ic_cdk::query] // Mark the function as a query method
+fn get_config() -> Config {
+    CONFIG.with(|c| c.borrow().get().clone())
+}
+
+#[ic_cdk::update] // Mark the function as an update method
+fn set_config(
+    low_stock_threshold: u32,
+    max_item_name_len: u32,
+    config_poll_secs: u64,
+    sync_page_size: u32,
+) -> Config {
+    CONFIG.with(|c| {
+        let mut config = c.borrow().get().clone();
+        config.low_stock_threshold = low_stock_threshold;
+        config.max_item_name_len = clamp_max_item_name_len(max_item_name_len);
+        config.config_poll_secs = config_poll_secs;
+        config.sync_page_size = clamp_sync_page_size(sync_page_size);
+        config.version += 1; // Bump the version so callers can detect the change
+
+        c.borrow_mut().set(config.clone()).expect("Cannot update config");
+        config
+    })
+}
+
+#[ic_cdk::query] // Mark the function as a query method
+fn list_low_stock() -> Vec<u64> {
+    LOW_STOCK.with(|low_stock| low_stock.borrow().clone())
+}
+
+// Recompute the full low-stock list from the current inventory and configured threshold
+fn sweep_low_stock() {
+    let threshold = CONFIG.with(|c| c.borrow().get().low_stock_threshold);
+    let ids: Vec<u64> = INVENTORY.with(|inventory| {
+        inventory
+            .borrow()
+            .iter()
+            .filter(|(_, item)| item.quantity <= threshold)
+            .map(|(id, _)| id)
+            .collect()
+    });
+    LOW_STOCK.with(|low_stock| *low_stock.borrow_mut() = ids);
+}
+
+// Incrementally update an item's membership in the low-stock list after a quantity change
+fn refresh_low_stock_membership(item_id: u64, quantity: u32) {
+    let threshold = CONFIG.with(|c| c.borrow().get().low_stock_threshold);
+    let is_low = quantity <= threshold;
+    LOW_STOCK.with(|low_stock| {
+        let mut ids = low_stock.borrow_mut();
+        let already_present = ids.contains(&item_id);
+        if is_low && !already_present {
+            ids.push(item_id);
+        } else if !is_low && already_present {
+            ids.retain(|id| *id != item_id);
+        }
+    });
 }
 
 #[derive(candid::CandidType, Serialize, Deserialize, Default)] // Derive macros for InventoryPayload struct
@@ -77,12 +363,432 @@ fn list_items() -> Vec<InventoryItem> {
     })
 }
 
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)] // Derive macros for SortKey enum
+enum SortKey {
+    #[default]
+    Name, // Sort by item name
+    Price, // Sort by item price
+    Quantity, // Sort by item quantity
+    CreatedAt, // Sort by item creation timestamp
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)] // Derive macros for SortDirection enum
+enum SortDirection {
+    #[default]
+    Ascending, // Lowest-to-highest ordering
+    Descending, // Highest-to-lowest ordering
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)] // Derive macros for ItemQuery struct
+struct ItemQuery {
+    name_contains: Option<String>, // Case-insensitive substring match on the item name
+    min_price: Option<f64>, // Lower bound (inclusive) on price
+    max_price: Option<f64>, // Upper bound (inclusive) on price
+    min_quantity: Option<u32>, // Lower bound (inclusive) on quantity
+    max_quantity: Option<u32>, // Upper bound (inclusive) on quantity
+    sort_key: SortKey, // Field to sort the matched items by
+    sort_direction: SortDirection, // Direction to sort the matched items in
+    offset: u64, // Number of matched items to skip before the returned page
+    limit: u32, // Maximum number of items to return in the page
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)] // Derive macros for ItemPage struct
+struct ItemPage {
+    items: Vec<InventoryItem>, // The requested page of matching items
+    total_matched: u64, // Total number of items that matched the query, before paging
+}
+
+#[ic_cdk::query] // Mark the function as a query method
+fn query_items(filter: ItemQuery) -> ItemPage {
+    // Gather every item that satisfies the filter predicate
+    let mut matched: Vec<InventoryItem> = INVENTORY.with(|inventory| {
+        inventory
+            .borrow()
+            .iter()
+            .map(|(_, item)| item.clone())
+            .filter(|item| matches_query(item, &filter))
+            .collect()
+    });
+
+    let total_matched = matched.len() as u64; // Record the match count before paging
+
+    sort_items(&mut matched, &filter.sort_key, &filter.sort_direction);
+
+    // Slice out the requested page
+    let items = matched
+        .into_iter()
+        .skip(filter.offset as usize)
+        .take(filter.limit as usize)
+        .collect();
+
+    ItemPage { items, total_matched }
+}
+
+// Check whether an item satisfies the name/price/quantity bounds of a query
+fn matches_query(item: &InventoryItem, filter: &ItemQuery) -> bool {
+    if let Some(needle) = &filter.name_contains {
+        if !item.name.to_lowercase().contains(&needle.to_lowercase()) {
+            return false;
+        }
+    }
+    if let Some(min_price) = filter.min_price {
+        if item.price < min_price {
+            return false;
+        }
+    }
+    if let Some(max_price) = filter.max_price {
+        if item.price > max_price {
+            return false;
+        }
+    }
+    if let Some(min_quantity) = filter.min_quantity {
+        if item.quantity < min_quantity {
+            return false;
+        }
+    }
+    if let Some(max_quantity) = filter.max_quantity {
+        if item.quantity > max_quantity {
+            return false;
+        }
+    }
+    true
+}
+
+// Sort items in place according to the requested key and direction
+fn sort_items(items: &mut [InventoryItem], key: &SortKey, direction: &SortDirection) {
+    items.sort_by(|a, b| {
+        let ordering = match key {
+            SortKey::Name => a.name.cmp(&b.name),
+            SortKey::Price => a
+                .price
+                .partial_cmp(&b.price)
+                .unwrap_or(std::cmp::Ordering::Equal),
+            SortKey::Quantity => a.quantity.cmp(&b.quantity),
+            SortKey::CreatedAt => a.created_at.cmp(&b.created_at),
+        };
+        match direction {
+            SortDirection::Ascending => ordering,
+            SortDirection::Descending => ordering.reverse(),
+        }
+    });
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default, PartialEq, Eq, PartialOrd, Ord)] // Derive macros for SyncCursor struct
+struct SyncCursor {
+    item_time: u64, // Change time of the last item id seen in the item stream
+    item_id: u64, // Id of the last item seen in the item stream, tiebreaking equal item_time
+    deleted_time: u64, // Tombstone time of the last deletion seen in the deletion stream
+    deleted_id: u64, // Id of the last deletion seen in the deletion stream, tiebreaking equal deleted_time
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)] // Derive macros for SyncBatch struct
+struct SyncBatch {
+    items: Vec<InventoryItem>, // Items created or updated since the cursor, capped at config.sync_page_size
+    deleted_ids: Vec<u64>, // Ids of items deleted since the cursor, capped at config.sync_page_size
+    next_cursor: SyncCursor, // Cursor to resume from on the next sync_since call
+    has_more: bool, // Whether more changed items or deletions remain beyond this page
+}
+
+#[ic_cdk::query] // Mark the function as a query method
+fn sync_since(cursor: SyncCursor) -> SyncBatch {
+    let sync_page_size = CONFIG.with(|c| c.borrow().get().sync_page_size) as usize;
+
+    // Gather every item changed after the client's last-seen (change_time, id), using the id as a
+    // tiebreaker so items sharing a boundary change_time (e.g. a single import_csv batch, where
+    // time() is constant for the whole call) are never skipped at the page boundary
+    let mut changed: Vec<InventoryItem> = INVENTORY.with(|inventory| {
+        inventory
+            .borrow()
+            .iter()
+            .map(|(_, item)| item.clone())
+            .filter(|item| {
+                let change_time = item.updated_at.unwrap_or(item.created_at);
+                (change_time, item.id) > (cursor.item_time, cursor.item_id)
+            })
+            .collect()
+    });
+    changed.sort_by_key(|item| (item.updated_at.unwrap_or(item.created_at), item.id));
+
+    let items_has_more = changed.len() > sync_page_size;
+    changed.truncate(sync_page_size);
+
+    // Resume from the last included item's (change_time, id) rather than time() - time() would
+    // skip every item past the page boundary until it happened to change again
+    let (next_item_time, next_item_id) = match changed.last() {
+        Some(last) if items_has_more => (last.updated_at.unwrap_or(last.created_at), last.id),
+        _ => (time(), 0),
+    };
+
+    // Gather every id tombstoned after the client's last-seen (deleted_time, id), with the same
+    // composite-cursor and paging treatment as the item stream above
+    let mut deleted: Vec<(u64, u64)> = DELETED_ITEMS.with(|deleted| {
+        deleted
+            .borrow()
+            .iter()
+            .map(|(id, deleted_at)| (deleted_at, id))
+            .filter(|&(deleted_at, id)| (deleted_at, id) > (cursor.deleted_time, cursor.deleted_id))
+            .collect()
+    });
+    deleted.sort_unstable();
+
+    let deletions_has_more = deleted.len() > sync_page_size;
+    deleted.truncate(sync_page_size);
+
+    let (next_deleted_time, next_deleted_id) = match deleted.last() {
+        Some(&(deleted_at, id)) if deletions_has_more => (deleted_at, id),
+        _ => (time(), 0),
+    };
+
+    SyncBatch {
+        items: changed,
+        deleted_ids: deleted.into_iter().map(|(_, id)| id).collect(),
+        next_cursor: SyncCursor {
+            item_time: next_item_time,
+            item_id: next_item_id,
+            deleted_time: next_deleted_time,
+            deleted_id: next_deleted_id,
+        },
+        has_more: items_has_more || deletions_has_more,
+    }
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)] // Derive macros for Conversion enum
+enum Conversion {
+    #[default]
+    AsIs, // Use the raw cell text unchanged
+    Integer, // Parse the cell as an i64
+    Float, // Parse the cell as an f64
+    Boolean, // Parse the cell as a bool ("true"/"false"/"1"/"0"/"yes"/"no")
+    TimestampFmt(String), // Parse the cell as a timestamp in the given format ("unix_s", "unix_ms" or "unix_ns")
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)] // Derive macros for ColumnMapping struct
+struct ColumnMapping {
+    name_column: String, // CSV header naming the item-name column
+    quantity_column: String, // CSV header naming the quantity column
+    quantity_conversion: Conversion, // How to parse the quantity column
+    price_column: String, // CSV header naming the price column
+    price_conversion: Conversion, // How to parse the price column
+    created_at_column: Option<String>, // Optional CSV header naming a creation-timestamp column
+    created_at_conversion: Conversion, // How to parse the creation-timestamp column
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize)] // Derive macros for ConversionError struct
+struct ConversionError {
+    row: u64, // Zero-based index of the offending data row, excluding the header
+    column: String, // Name of the offending column, or "row" for a whole-row validation failure
+    raw: String, // The raw cell text that failed to convert or validate
+    expected: String, // Human-readable description of what was expected
+}
+
+#[derive(candid::CandidType, Clone, Serialize, Deserialize, Default)] // Derive macros for ImportReport struct
+struct ImportReport {
+    created_ids: Vec<u64>, // Ids of items successfully created by this import
+    failures: Vec<ConversionError>, // Per-row conversion or validation failures
+}
+
+// Intermediate result of converting a single CSV cell according to its Conversion
+enum ConvertedValue {
+    Text(String),
+    Integer(i64),
+    Float(f64),
+    Boolean(bool),
+    Timestamp(u64),
+}
+
+// Convert a raw CSV cell into a typed value according to the column's Conversion
+fn convert_cell(row: u64, column: &str, raw: &str, conversion: &Conversion) -> Result<ConvertedValue, ConversionError> {
+    match conversion {
+        Conversion::AsIs => Ok(ConvertedValue::Text(raw.to_string())),
+        Conversion::Integer => raw.parse::<i64>().map(ConvertedValue::Integer).map_err(|_| ConversionError {
+            row,
+            column: column.to_string(),
+            raw: raw.to_string(),
+            expected: "an integer".to_string(),
+        }),
+        Conversion::Float => raw.parse::<f64>().map(ConvertedValue::Float).map_err(|_| ConversionError {
+            row,
+            column: column.to_string(),
+            raw: raw.to_string(),
+            expected: "a floating point number".to_string(),
+        }),
+        Conversion::Boolean => match raw.to_lowercase().as_str() {
+            "true" | "1" | "yes" => Ok(ConvertedValue::Boolean(true)),
+            "false" | "0" | "no" => Ok(ConvertedValue::Boolean(false)),
+            _ => Err(ConversionError {
+                row,
+                column: column.to_string(),
+                raw: raw.to_string(),
+                expected: "a boolean".to_string(),
+            }),
+        },
+        Conversion::TimestampFmt(fmt) => parse_timestamp(raw, fmt)
+            .map(ConvertedValue::Timestamp)
+            .ok_or_else(|| ConversionError {
+                row,
+                column: column.to_string(),
+                raw: raw.to_string(),
+                expected: format!("a timestamp matching '{}'", fmt),
+            }),
+    }
+}
+
+// Parse a raw integer as a timestamp in the given unit; supported formats are "unix_s", "unix_ms" and "unix_ns"
+fn parse_timestamp(raw: &str, fmt: &str) -> Option<u64> {
+    let parsed: u64 = raw.parse().ok()?;
+    match fmt {
+        "unix_s" => Some(parsed.saturating_mul(1_000_000_000)),
+        "unix_ms" => Some(parsed.saturating_mul(1_000_000)),
+        "unix_ns" => Some(parsed),
+        _ => None,
+    }
+}
+
+#[ic_cdk::update] // Mark the function as an update method
+fn import_csv(data: String, mapping: ColumnMapping) -> ImportReport {
+    let mut created_ids = Vec::new();
+    let mut failures = Vec::new();
+
+    let mut lines = data.lines();
+    let header_line = match lines.next() {
+        Some(line) => line,
+        None => return ImportReport { created_ids, failures },
+    };
+    let headers: Vec<&str> = header_line.split(',').map(|h| h.trim()).collect();
+
+    for (row_index, line) in lines.enumerate() {
+        if line.trim().is_empty() {
+            continue; // Skip blank lines
+        }
+        let row = row_index as u64; // Data rows are numbered from 0, excluding the header
+        let cells: Vec<&str> = line.split(',').map(|c| c.trim()).collect();
+        let cell_for = |column: &str| -> &str {
+            headers
+                .iter()
+                .position(|h| *h == column)
+                .and_then(|idx| cells.get(idx))
+                .copied()
+                .unwrap_or("")
+        };
+
+        let name = cell_for(&mapping.name_column).to_string();
+
+        let quantity = match convert_cell(
+            row,
+            &mapping.quantity_column,
+            cell_for(&mapping.quantity_column),
+            &mapping.quantity_conversion,
+        ) {
+            Ok(ConvertedValue::Integer(value)) if value >= 0 => value as u32,
+            Ok(ConvertedValue::Integer(value)) => {
+                failures.push(ConversionError {
+                    row,
+                    column: mapping.quantity_column.clone(),
+                    raw: value.to_string(),
+                    expected: "a non-negative integer".to_string(),
+                });
+                continue;
+            }
+            Ok(_) => {
+                failures.push(ConversionError {
+                    row,
+                    column: mapping.quantity_column.clone(),
+                    raw: cell_for(&mapping.quantity_column).to_string(),
+                    expected: "an integer".to_string(),
+                });
+                continue;
+            }
+            Err(err) => {
+                failures.push(err);
+                continue;
+            }
+        };
+
+        let price = match convert_cell(
+            row,
+            &mapping.price_column,
+            cell_for(&mapping.price_column),
+            &mapping.price_conversion,
+        ) {
+            Ok(ConvertedValue::Float(value)) => value,
+            Ok(_) => {
+                failures.push(ConversionError {
+                    row,
+                    column: mapping.price_column.clone(),
+                    raw: cell_for(&mapping.price_column).to_string(),
+                    expected: "a floating point number".to_string(),
+                });
+                continue;
+            }
+            Err(err) => {
+                failures.push(err);
+                continue;
+            }
+        };
+
+        let created_at = match &mapping.created_at_column {
+            Some(column) => {
+                let raw = cell_for(column);
+                if raw.is_empty() {
+                    None
+                } else {
+                    match convert_cell(row, column, raw, &mapping.created_at_conversion) {
+                        Ok(ConvertedValue::Timestamp(value)) => Some(value),
+                        Ok(_) => {
+                            failures.push(ConversionError {
+                                row,
+                                column: column.clone(),
+                                raw: raw.to_string(),
+                                expected: "a timestamp".to_string(),
+                            });
+                            continue;
+                        }
+                        Err(err) => {
+                            failures.push(err);
+                            continue;
+                        }
+                    }
+                }
+            }
+            None => None,
+        };
+
+        let payload = InventoryPayload { name, quantity, price };
+
+        // Reuse add_item's validation rules so bad rows fail with the same messages
+        match add_item(payload) {
+            Ok(mut item) => {
+                if let Some(created_at) = created_at {
+                    item.created_at = created_at;
+                    do_insert(&item);
+                }
+                created_ids.push(item.id);
+            }
+            Err(Error::InvalidInput { msg }) => failures.push(ConversionError {
+                row,
+                column: "row".to_string(),
+                raw: line.to_string(),
+                expected: msg,
+            }),
+            Err(Error::NotFound { .. }) => unreachable!("add_item only returns InvalidInput or Ok"),
+        }
+    }
+
+    ImportReport { created_ids, failures }
+}
+
 #[ic_cdk::update] // Mark the function as an update method
 fn add_item(payload: InventoryPayload) -> Result<InventoryItem, Error> {
     // Validate input payload
     if payload.name.is_empty() {
         return Err(Error::InvalidInput { msg: "Name must be provided and non-empty".to_string() });
     }
+    let max_item_name_len = CONFIG.with(|c| c.borrow().get().max_item_name_len);
+    if payload.name.chars().count() as u32 > max_item_name_len {
+        return Err(Error::InvalidInput {
+            msg: format!("Name must be at most {} characters", max_item_name_len),
+        });
+    }
     if payload.quantity == 0 {
         return Err(Error::InvalidInput { msg: "Quantity must be greater than zero".to_string() });
     }
@@ -111,6 +817,10 @@ fn add_item(payload: InventoryPayload) -> Result<InventoryItem, Error> {
     // Insert the new item into inventory
     do_insert(&item);
 
+    // Record the opening stock movement for the new item
+    record_movement(id, item.quantity as i64, MovementReason::Add, item.quantity);
+    refresh_low_stock_membership(id, item.quantity);
+
     Ok(item)
 }
 
@@ -120,6 +830,12 @@ fn update_item(id: u64, payload: InventoryPayload) -> Result<InventoryItem, Erro
     if payload.name.is_empty() {
         return Err(Error::InvalidInput { msg: "Name must be provided and non-empty".to_string() });
     }
+    let max_item_name_len = CONFIG.with(|c| c.borrow().get().max_item_name_len);
+    if payload.name.chars().count() as u32 > max_item_name_len {
+        return Err(Error::InvalidInput {
+            msg: format!("Name must be at most {} characters", max_item_name_len),
+        });
+    }
     if payload.quantity == 0 {
         return Err(Error::InvalidInput { msg: "Quantity must be greater than zero".to_string() });
     }
@@ -130,16 +846,20 @@ fn update_item(id: u64, payload: InventoryPayload) -> Result<InventoryItem, Erro
     // Fetch the existing item
     match INVENTORY.with(|inventory| inventory.borrow().get(&id)) {
         Some(mut item) => {
-            // Update item details
+            // Update name/price directly; quantity goes through apply_movement below
+            let quantity_delta = payload.quantity as i64 - item.quantity as i64;
             item.name = payload.name;
-            item.quantity = payload.quantity;
             item.price = payload.price;
             item.updated_at = Some(time()); // Set the update timestamp
 
             // Update the item in inventory
             do_insert(&item);
 
-            Ok(item)
+            if quantity_delta != 0 {
+                apply_movement(id, quantity_delta, MovementReason::Adjustment)?;
+            }
+
+            Ok(_get_item(&id).expect("item was just inserted"))
         }
         None => Err(Error::NotFound {
             msg: format!("Couldn't update an item with id={}. Item not found.", id),
@@ -156,13 +876,156 @@ fn do_insert(item: &InventoryItem) {
 fn delete_item(id: u64) -> Result<InventoryItem, Error> {
     // Remove the item from inventory
     match INVENTORY.with(|inventory| inventory.borrow_mut().remove(&id)) {
-        Some(item) => Ok(item), // Return the deleted item if found
+        Some(item) => {
+            // Record the closing stock movement for the removed item
+            record_movement(id, -(item.quantity as i64), MovementReason::Delete, 0);
+            // Tombstone the id so sync_since can propagate the deletion to offline clients
+            DELETED_ITEMS.with(|deleted| deleted.borrow_mut().insert(id, time()));
+            LOW_STOCK.with(|low_stock| low_stock.borrow_mut().retain(|low_id| *low_id != id));
+            Ok(item) // Return the deleted item
+        }
         None => Err(Error::NotFound {
             msg: format!("Couldn't delete an item with id={}. Item not found.", id),
         }),
     }
 }
 
+// Apply a signed quantity change to an item, rejecting anything that would take it below zero,
+// and record the resulting movement in the ledger
+fn apply_movement(item_id: u64, delta: i64, reason: MovementReason) -> Result<StockMovement, Error> {
+    let mut item = match INVENTORY.with(|inventory| inventory.borrow().get(&item_id)) {
+        Some(item) => item,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("An item with id={} not found", item_id),
+            })
+        }
+    };
+
+    let new_balance = item.quantity as i64 + delta;
+    if new_balance < 0 {
+        return Err(Error::InvalidInput {
+            msg: format!(
+                "Movement would take item id={} below zero stock (have {}, delta {})",
+                item_id, item.quantity, delta
+            ),
+        });
+    }
+
+    item.quantity = new_balance as u32;
+    item.updated_at = Some(time());
+    do_insert(&item);
+    refresh_low_stock_membership(item_id, item.quantity);
+
+    Ok(record_movement(item_id, delta, reason, item.quantity))
+}
+
+// Append a movement to the ledger under a fresh monotonic id
+fn record_movement(item_id: u64, delta: i64, reason: MovementReason, balance_after: u32) -> StockMovement {
+    let id = MOVEMENT_ID_COUNTER
+        .with(|counter| {
+            let current_value = *counter.borrow().get();
+            counter.borrow_mut().set(current_value + 1)
+        })
+        .expect("Cannot increment movement counter");
+
+    let movement = StockMovement {
+        id,
+        item_id,
+        delta,
+        reason,
+        balance_after,
+        at: time(),
+    };
+
+    MOVEMENTS.with(|movements| movements.borrow_mut().insert(movement.id, movement.clone()));
+
+    movement
+}
+
+#[ic_cdk::update] // Mark the function as an update method
+fn restock(item_id: u64, qty: u32) -> Result<StockMovement, Error> {
+    apply_movement(item_id, qty as i64, MovementReason::Restock)
+}
+
+#[ic_cdk::update] // Mark the function as an update method
+fn record_sale(item_id: u64, qty: u32) -> Result<StockMovement, Error> {
+    apply_movement(item_id, -(qty as i64), MovementReason::Sale)
+}
+
+#[ic_cdk::query] // Mark the function as a query method
+fn list_movements(item_id: u64, since: Option<u64>) -> Vec<StockMovement> {
+    let since = since.unwrap_or(0);
+    MOVEMENTS.with(|movements| {
+        movements
+            .borrow()
+            .iter()
+            .map(|(_, movement)| movement.clone())
+            .filter(|movement| movement.item_id == item_id && movement.at > since)
+            .collect()
+    })
+}
+
+// Hold stock against a pending checkout without yet treating it as sold
+#[ic_cdk::update] // Mark the function as an update method
+fn reserve_stock(item_id: u64, qty: u32) -> Result<Reservation, Error> {
+    let movement = apply_movement(item_id, -(qty as i64), MovementReason::Reserve)?;
+
+    let reservation = Reservation {
+        id: movement.id,
+        item_id,
+        quantity: qty,
+        movement_id: movement.id,
+    };
+    RESERVATIONS.with(|reservations| {
+        reservations
+            .borrow_mut()
+            .insert(reservation.id, reservation.clone())
+    });
+
+    Ok(reservation)
+}
+
+// Finalize a reservation into a completed sale; the stock was already decremented at reserve time
+#[ic_cdk::update] // Mark the function as an update method
+fn commit_reservation(reservation_id: u64) -> Result<(), Error> {
+    match RESERVATIONS.with(|reservations| reservations.borrow_mut().remove(&reservation_id)) {
+        Some(reservation) => {
+            record_movement(
+                reservation.item_id,
+                0,
+                MovementReason::ReservationCommit,
+                _get_item(&reservation.item_id)
+                    .map(|item| item.quantity)
+                    .unwrap_or(0),
+            );
+            Ok(())
+        }
+        None => Err(Error::NotFound {
+            msg: format!("A reservation with id={} not found", reservation_id),
+        }),
+    }
+}
+
+// Roll back a reservation, returning its held stock to the item
+#[ic_cdk::update] // Mark the function as an update method
+fn release_reservation(reservation_id: u64) -> Result<StockMovement, Error> {
+    let reservation = match RESERVATIONS.with(|reservations| reservations.borrow_mut().remove(&reservation_id)) {
+        Some(reservation) => reservation,
+        None => {
+            return Err(Error::NotFound {
+                msg: format!("A reservation with id={} not found", reservation_id),
+            })
+        }
+    };
+
+    apply_movement(
+        reservation.item_id,
+        reservation.quantity as i64,
+        MovementReason::ReservationRelease,
+    )
+}
+
 #[derive(candid::CandidType, Deserialize, Serialize)] // Derive macros for the Error enum
 enum Error {
     NotFound { msg: String }, // Error variant for not found
@@ -176,3 +1039,45 @@ fn _get_item(id: &u64) -> Option<InventoryItem> {
 
 // Generate candid interface
 ic_cdk::export_candid!();
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_item() -> InventoryItem {
+        InventoryItem {
+            id: 42,
+            name: "Wireless Mechanical Keyboard".to_string(),
+            quantity: 7,
+            price: 129.99,
+            created_at: 1_700_000_000_000_000_000,
+            updated_at: Some(1_700_000_500_000_000_000),
+        }
+    }
+
+    #[test]
+    fn candid_and_cbor_round_trip_to_identical_items() {
+        let item = sample_item();
+
+        let candid_bytes = CandidCodec::encode(&item);
+        let cbor_bytes = CborCodec::encode(&item);
+
+        assert_eq!(CandidCodec::decode(&candid_bytes), item);
+        assert_eq!(CborCodec::decode(&cbor_bytes), item);
+    }
+
+    #[test]
+    fn cbor_is_no_larger_than_candid_for_a_representative_item() {
+        let item = sample_item();
+
+        let candid_len = CandidCodec::encode(&item).len();
+        let cbor_len = CborCodec::encode(&item).len();
+
+        assert!(
+            cbor_len <= candid_len,
+            "expected CBOR encoding ({} bytes) to be no larger than Candid ({} bytes)",
+            cbor_len,
+            candid_len
+        );
+    }
+}